@@ -2,24 +2,25 @@ use clap::{CommandFactory, ErrorKind, Parser};
 use csv;
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::one_of,
-    combinator::{fail, recognize},
-    multi::{many1, separated_list1},
-    sequence::{preceded, separated_pair, terminated},
+    bytes::complete::{tag, take_while1},
+    character::complete::{alpha1, alphanumeric1, one_of},
+    combinator::{fail, opt, recognize},
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated},
     IResult,
 };
+use regex::Regex;
 use serde_json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert;
 use std::io;
 
 /* parsers for target */
 
 /// Parse a natural number.
-fn natural(input: &str) -> IResult<&str, u8> {
+fn natural(input: &str) -> IResult<&str, usize> {
     let (input, value) = recognize(many1(one_of("0123456789")))(input)?;
-    let v: u8 = value.parse().unwrap();
+    let v: usize = value.parse().unwrap();
     if v < 1 {
         fail(input)
     } else {
@@ -32,27 +33,62 @@ fn natural(input: &str) -> IResult<&str, u8> {
 pub enum Range {
     /// Column.
     /// e.g. 3
-    Single(u8),
+    Single(usize),
     /// Left limited range.
     /// `Left(x)` selects all columns from x to the last number.
     /// e.g. 4-
-    Left(u8),
+    Left(usize),
     /// Right limited range.
     /// `Right(x)` selects all columns from 1 to the x.
     /// e.g. -5
-    Right(u8),
+    Right(usize),
     /// Inclusive interval.
+    /// A left limit greater than the right limit selects columns in
+    /// descending order instead of failing, e.g. 9-7 reorders as 9,8,7.
     /// e.g. 7-9
-    Interval(u8, u8),
+    Interval(usize, usize),
+    /// Every column, in order.
+    /// e.g. -
+    All,
+    /// Column selected by header name.
+    /// Only valid when `--header` is set.
+    /// e.g. name
+    Name(String),
+    /// Inclusive interval between two header names.
+    /// Only valid when `--header` is set.
+    /// e.g. name_a-name_b
+    NameInterval(String, String),
+    /// Every header name matching this regular expression, in header order.
+    /// Only valid when `--header` is set.
+    /// e.g. /^addr_/
+    Regex(String),
 }
 
 impl Range {
-    fn ends(&self) -> (usize, usize) {
+    /// Indices (0-based) this range selects out of a row of length `rlen`,
+    /// in the order they should appear in the output.
+    fn indices(&self, rlen: usize) -> Vec<usize> {
         match self {
-            Range::Single(x) => (*x as usize, *x as usize + 1),
-            Range::Left(x) => (*x as usize, usize::MAX),
-            Range::Right(x) => (0, *x as usize + 1),
-            Range::Interval(x, y) => (*x as usize, *y as usize + 1),
+            Range::Single(x) => {
+                if *x < rlen {
+                    vec![*x]
+                } else {
+                    Vec::new()
+                }
+            }
+            Range::Left(x) => (*x..rlen).collect(),
+            Range::Right(x) => (0..rlen).filter(|i| *i <= *x).collect(),
+            Range::Interval(x, y) => {
+                if x <= y {
+                    (*x..=*y).filter(|i| *i < rlen).collect()
+                } else {
+                    (*y..=*x).rev().filter(|i| *i < rlen).collect()
+                }
+            }
+            Range::All => (0..rlen).collect(),
+            Range::Name(_) | Range::NameInterval(_, _) | Range::Regex(_) => {
+                unreachable!("Range::Name/NameInterval/Regex must be resolved before indices()")
+            }
         }
     }
 }
@@ -77,37 +113,143 @@ fn interval(input: &str) -> IResult<&str, Range> {
     Ok((input, Range::Interval(left_limit - 1, right_limit - 1)))
 }
 
+/// Parse a lone `-`, meaning every column.
+fn all(input: &str) -> IResult<&str, Range> {
+    let (input, _) = tag("-")(input)?;
+    Ok((input, Range::All))
+}
+
+/// Parse an identifier-like header name, e.g. `name` or `_name2`.
+fn identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+fn name(input: &str) -> IResult<&str, Range> {
+    let (input, value) = identifier(input)?;
+    Ok((input, Range::Name(value.to_string())))
+}
+
+fn name_interval(input: &str) -> IResult<&str, Range> {
+    let (input, (left_name, right_name)) = separated_pair(identifier, tag("-"), identifier)(input)?;
+    Ok((
+        input,
+        Range::NameInterval(left_name.to_string(), right_name.to_string()),
+    ))
+}
+
+/// Parse a slash-delimited regex, e.g. `/^addr_/`.
+fn regex_range(input: &str) -> IResult<&str, Range> {
+    let (input, pattern) = delimited(tag("/"), take_while1(|c| c != '/'), tag("/"))(input)?;
+    Ok((input, Range::Regex(pattern.to_string())))
+}
+
 fn range(input: &str) -> IResult<&str, Range> {
-    alt((interval, right, left, single))(input)
+    alt((
+        interval,
+        right,
+        left,
+        single,
+        name_interval,
+        name,
+        regex_range,
+        all,
+    ))(input)
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Target {
+    /// Select every column except those matched by `ranges` when `true`.
+    pub invert: bool,
     pub ranges: Vec<Range>,
 }
 
 fn target(input: &str) -> IResult<&str, Target> {
+    let (input, invert) = opt(tag("!"))(input)?;
     let (input, ranges) = separated_list1(tag(","), range)(input)?;
-    Ok((input, Target { ranges }))
+    Ok((
+        input,
+        Target {
+            invert: invert.is_some(),
+            ranges,
+        },
+    ))
 }
 
 /* field selector */
 
 impl Target {
-    /// Cut out selected portions of the row.
-    fn select(&self, row: impl TargetRow) -> ResultRow {
-        let rlen = row.len();
-        let it = self.ranges.iter().map(|x| x.ends());
-        let it = it.map(|(left, right)| {
-            let v: Vec<usize> = (0..rlen).filter(|i| *i >= left && *i < right).collect();
-            v
+    /// Resolve any header-name-based ranges into index-based ranges using `headers`.
+    ///
+    /// Returns an error if a name selector is used without `headers`, or if a
+    /// name does not match any header.
+    fn resolve_names(&self, headers: Option<&RecordRow>) -> Result<Target, String> {
+        let needs_headers = self.ranges.iter().any(|r| {
+            matches!(
+                r,
+                Range::Name(_) | Range::NameInterval(_, _) | Range::Regex(_)
+            )
         });
-        let mut v = Vec::new();
-        for indexes in it {
-            for idx in indexes {
-                v.push(row.get(idx).unwrap().to_owned());
+        if !needs_headers {
+            return Ok(self.clone());
+        }
+        let headers = headers.ok_or_else(|| {
+            "column names and regexes in --target require --header to be set".to_string()
+        })?;
+        let index_of = |name: &str| -> Result<usize, String> {
+            (0..headers.len())
+                .find(|&i| headers.get(i) == Some(name))
+                .ok_or_else(|| format!("unknown column name: {}", name))
+        };
+        let mut ranges = Vec::with_capacity(self.ranges.len());
+        for r in &self.ranges {
+            match r {
+                Range::Name(n) => ranges.push(Range::Single(index_of(n)?)),
+                Range::NameInterval(a, b) => {
+                    ranges.push(Range::Interval(index_of(a)?, index_of(b)?))
+                }
+                Range::Regex(pattern) => {
+                    let re = Regex::new(pattern)
+                        .map_err(|err| format!("invalid regex {}: {}", pattern, err))?;
+                    for i in 0..headers.len() {
+                        if headers.get(i).is_some_and(|h| re.is_match(h)) {
+                            ranges.push(Range::Single(i));
+                        }
+                    }
+                }
+                other => ranges.push(other.clone()),
             }
         }
+        Ok(Target {
+            invert: self.invert,
+            ranges,
+        })
+    }
+
+    /// Indices selected by `ranges`, in the order the ranges are listed.
+    fn selected_indices(&self, rlen: usize) -> Vec<usize> {
+        let mut v = Vec::new();
+        for r in &self.ranges {
+            v.extend(r.indices(rlen));
+        }
+        v
+    }
+
+    /// Cut out selected portions of the row.
+    fn select(&self, row: impl TargetRow) -> ResultRow {
+        let rlen = row.len();
+        let indices: Vec<usize> = if self.invert {
+            let selected: HashSet<usize> = self.selected_indices(rlen).into_iter().collect();
+            (0..rlen).filter(|i| !selected.contains(i)).collect()
+        } else {
+            self.selected_indices(rlen)
+        };
+        let v = indices
+            .into_iter()
+            .map(|idx| row.get(idx).unwrap().to_owned())
+            .collect();
         ResultRow(v)
     }
 }
@@ -180,17 +322,26 @@ fn read_csv(delimiter: u8, need_headers: bool) -> Input {
 struct ResultWriter {
     json: bool,
     headers: Option<ResultRow>,
+    writer: csv::Writer<io::Stdout>,
 }
 
 impl ResultWriter {
-    fn new(json: bool, target: &Target, headers: Option<RecordRow>) -> ResultWriter {
+    fn new(
+        json: bool,
+        target: &Target,
+        headers: Option<RecordRow>,
+        output_delimiter: u8,
+    ) -> ResultWriter {
         ResultWriter {
             json,
             headers: headers.map(|x| target.select(x)),
+            writer: csv::WriterBuilder::new()
+                .delimiter(output_delimiter)
+                .from_writer(io::stdout()),
         }
     }
     /// Write the result into stdout, or the error into stderr.
-    fn write(&self, r: Result<ResultRow, csv::Error>) {
+    fn write(&mut self, r: Result<ResultRow, csv::Error>) {
         match r {
             Err(err) => eprintln!("{}", err),
             Ok(r) => {
@@ -202,12 +353,17 @@ impl ResultWriter {
             }
         }
     }
-    fn write_csv(&self, r: ResultRow) {
+    fn write_csv(&mut self, r: ResultRow) {
         let v: Vec<_> = r.into();
-        let v = v.join(",");
-        println!("{}", v);
+        if let Err(err) = self.writer.write_record(&v) {
+            eprintln!("{}", err);
+            return;
+        }
+        if let Err(err) = self.writer.flush() {
+            eprintln!("{}", err);
+        }
     }
-    fn write_json(&self, r: ResultRow) {
+    fn write_json(&mut self, r: ResultRow) {
         if self.headers.is_none() {
             self.write_json_array(r)
         } else {
@@ -245,6 +401,17 @@ fn main() {
         cmd.error(ErrorKind::InvalidValue, "Delimiter expect 1 byte character")
             .exit();
     }
+    if let Some(d) = cli.output_delimiter {
+        if d.len_utf8() > 1 {
+            let mut cmd = Cli::command();
+            cmd.error(
+                ErrorKind::InvalidValue,
+                "Output delimiter expect 1 byte character",
+            )
+            .exit();
+        }
+    }
+    let output_delimiter = cli.output_delimiter.unwrap_or(cli.delimiter) as u8;
 
     match target(&cli.target) {
         Err(err) => {
@@ -254,9 +421,16 @@ fn main() {
         }
         // normal case
         Ok(("", tgt)) => {
-            let t = &tgt;
             let input = read_csv(cli.delimiter as u8, cli.header);
-            let writer = ResultWriter::new(cli.json, t, input.1);
+            let resolved = match tgt.resolve_names(input.1.as_ref()) {
+                Err(msg) => {
+                    let mut cmd = Cli::command();
+                    cmd.error(ErrorKind::InvalidValue, msg).exit();
+                }
+                Ok(t) => t,
+            };
+            let t = &resolved;
+            let mut writer = ResultWriter::new(cli.json, t, input.1, output_delimiter);
             input
                 .0
                 .map(|x| x.map(|z| t.select(z)))
@@ -319,11 +493,44 @@ struct Cli {
     /// 1,3,4
     /// 11,13,14
     /// ```
+    /// Name, requires --header:
+    /// ```
+    /// ❯ (echo 'a,b,c';echo '2,3,4';echo '11,12,13') | csvcut -f b --header
+    /// 3
+    /// 12
+    /// ```
+    /// Inverted, select every column except those listed:
+    /// ```
+    /// ❯ (echo 'a,b,c,d';echo '1,2,3,4';echo '11,12,13,14') | csvcut -f '!2,4'
+    /// a,c
+    /// 1,3
+    /// 11,13
+    /// ```
+    /// Regex, matches headers and requires --header:
+    /// ```
+    /// ❯ (echo 'id,addr_a,addr_b';echo '1,tokyo,osaka') | csvcut -f '/^addr_/' --header
+    /// tokyo,osaka
+    /// ```
+    /// All columns:
+    /// ```
+    /// ❯ (echo 'a,b,c';echo '1,2,3') | csvcut -f -
+    /// a,b,c
+    /// 1,2,3
+    /// ```
+    /// Reversed interval, reorders columns:
+    /// ```
+    /// ❯ (echo 'a,b,c';echo '1,2,3') | csvcut -f 3-1
+    /// c,b,a
+    /// 3,2,1
+    /// ```
     #[clap(short = 'f', long, allow_hyphen_values = true, verbatim_doc_comment)]
     target: String,
     /// Use DELIMITER as the field delimiter character instead of the ','.
     #[clap(short, long, default_value = ",")]
     delimiter: char,
+    /// Use OUTPUT_DELIMITER as the field delimiter character for output instead of --delimiter.
+    #[clap(long)]
+    output_delimiter: Option<char>,
     /// Print results as json.
     ///
     /// e.g.
@@ -375,13 +582,14 @@ mod tests {
     // error
     test_select!(
         select_none,
-        Target { ranges: vec![] },
+        Target { invert: false, ranges: vec![] },
         vec!["top"],
         empty_strs()
     );
     test_select!(
         select_from_none,
         Target {
+            invert: false,
             ranges: vec![Range::Single(0)],
         },
         empty_strs(),
@@ -391,6 +599,7 @@ mod tests {
     test_select!(
         select_single,
         Target {
+            invert: false,
             ranges: vec![Range::Single(0)],
         },
         vec!["top"],
@@ -399,6 +608,7 @@ mod tests {
     test_select!(
         select_single_failure,
         Target {
+            invert: false,
             ranges: vec![Range::Single(1)],
         },
         vec!["top"],
@@ -408,6 +618,7 @@ mod tests {
     test_select!(
         select_left_over_right,
         Target {
+            invert: false,
             ranges: vec![Range::Left(2)],
         },
         vec!["top"],
@@ -416,6 +627,7 @@ mod tests {
     test_select!(
         select_left_center,
         Target {
+            invert: false,
             ranges: vec![Range::Left(1)],
         },
         vec!["top", "two"],
@@ -424,6 +636,7 @@ mod tests {
     test_select!(
         select_left,
         Target {
+            invert: false,
             ranges: vec![Range::Left(0)],
         },
         vec!["top", "two"],
@@ -433,6 +646,7 @@ mod tests {
     test_select!(
         select_right_over_right,
         Target {
+            invert: false,
             ranges: vec![Range::Right(3)],
         },
         vec!["top", "two"],
@@ -441,6 +655,7 @@ mod tests {
     test_select!(
         select_right_center,
         Target {
+            invert: false,
             ranges: vec![Range::Right(0)],
         },
         vec!["top", "two"],
@@ -449,6 +664,7 @@ mod tests {
     test_select!(
         select_right,
         Target {
+            invert: false,
             ranges: vec![Range::Right(1)],
         },
         vec!["top", "two"],
@@ -458,6 +674,7 @@ mod tests {
     test_select!(
         select_interval_out_of_bounds,
         Target {
+            invert: false,
             ranges: vec![Range::Interval(2, 3)],
         },
         vec!["top"],
@@ -466,6 +683,7 @@ mod tests {
     test_select!(
         select_interval_right_out_of_bounds,
         Target {
+            invert: false,
             ranges: vec![Range::Interval(0, 3)],
         },
         vec!["top"],
@@ -474,23 +692,36 @@ mod tests {
     test_select!(
         select_interval_single,
         Target {
+            invert: false,
             ranges: vec![Range::Interval(1, 1)],
         },
         vec!["top", "two"],
         vec!["two"]
     );
     test_select!(
-        select_interval_negative,
+        select_interval_reversed,
         Target {
+            invert: false,
             ranges: vec![Range::Interval(1, 0)],
         },
         vec!["top", "two"],
-        empty_strs()
+        vec!["two", "top"]
+    );
+    // Range::All
+    test_select!(
+        select_all,
+        Target {
+            invert: false,
+            ranges: vec![Range::All],
+        },
+        vec!["top", "two", "three"],
+        vec!["top", "two", "three"]
     );
     // Range::Single + Range::Interval
     test_select!(
         select_single_and_interval,
         Target {
+            invert: false,
             ranges: vec![Range::Single(0), Range::Interval(3, 4)],
         },
         vec!["0", "1", "2", "3", "4", "5"],
@@ -499,11 +730,31 @@ mod tests {
     test_select!(
         select_single_and_interval_crossing,
         Target {
+            invert: false,
             ranges: vec![Range::Single(3), Range::Interval(2, 4)],
         },
         vec!["0", "1", "2", "3", "4", "5"],
         vec!["3", "2", "3", "4"]
     );
+    // invert
+    test_select!(
+        select_invert_single,
+        Target {
+            invert: true,
+            ranges: vec![Range::Single(1)],
+        },
+        vec!["0", "1", "2", "3"],
+        vec!["0", "2", "3"]
+    );
+    test_select!(
+        select_invert_interval,
+        Target {
+            invert: true,
+            ranges: vec![Range::Interval(1, 2)],
+        },
+        vec!["0", "1", "2", "3"],
+        vec!["0", "3"]
+    );
 
     macro_rules! test_target {
         ($name:ident, $input:expr, $want:expr) => {
@@ -521,6 +772,7 @@ mod tests {
         Ok((
             "",
             Target {
+                invert: false,
                 ranges: vec![Range::Single(1)]
             }
         ))
@@ -532,6 +784,7 @@ mod tests {
         Ok((
             "",
             Target {
+                invert: false,
                 ranges: vec![Range::Interval(1, 2), Range::Single(3)]
             }
         ))
@@ -543,6 +796,7 @@ mod tests {
         Ok((
             "",
             Target {
+                invert: false,
                 ranges: vec![Range::Left(1), Range::Right(3)]
             }
         ))
@@ -554,6 +808,7 @@ mod tests {
         Ok((
             "",
             Target {
+                invert: false,
                 ranges: vec![
                     Range::Interval(1, 2),
                     Range::Interval(5, 5),
@@ -563,6 +818,18 @@ mod tests {
         ))
     );
 
+    test_target!(
+        parse_target_inverted,
+        "!2,4-",
+        Ok((
+            "",
+            Target {
+                invert: true,
+                ranges: vec![Range::Single(1), Range::Left(3)]
+            }
+        ))
+    );
+
     macro_rules! test_range {
         ($name:ident, $input:expr, $want:expr) => {
             #[test]
@@ -577,4 +844,114 @@ mod tests {
     test_range!(parse_left, "3-", Ok(("", Range::Left(2))));
     test_range!(parse_right, "-10", Ok(("", Range::Right(9))));
     test_range!(parse_interval, "4-8", Ok(("", Range::Interval(3, 7))));
+    test_range!(parse_single_wide, "300", Ok(("", Range::Single(299))));
+    test_range!(parse_left_wide, "1000-", Ok(("", Range::Left(999))));
+    test_range!(parse_all, "-", Ok(("", Range::All)));
+    test_range!(
+        parse_interval_reversed,
+        "9-7",
+        Ok(("", Range::Interval(8, 6)))
+    );
+    test_range!(parse_name, "email", Ok(("", Range::Name("email".to_string()))));
+    test_range!(
+        parse_regex,
+        "/^addr_/",
+        Ok(("", Range::Regex("^addr_".to_string())))
+    );
+    test_range!(
+        parse_name_interval,
+        "name-email",
+        Ok((
+            "",
+            Range::NameInterval("name".to_string(), "email".to_string())
+        ))
+    );
+
+    fn record_row(fields: &[&str]) -> RecordRow {
+        RecordRow::new(csv::StringRecord::from(fields.to_vec()))
+    }
+
+    #[test]
+    fn resolve_names_without_names_is_unchanged() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Single(0), Range::Interval(1, 2)],
+        };
+        assert_eq!(Ok(t.clone()), t.resolve_names(None));
+    }
+
+    #[test]
+    fn resolve_names_requires_headers() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Name("b".to_string())],
+        };
+        assert!(t.resolve_names(None).is_err());
+    }
+
+    #[test]
+    fn resolve_names_unknown_name() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Name("z".to_string())],
+        };
+        let headers = record_row(&["a", "b", "c"]);
+        assert!(t.resolve_names(Some(&headers)).is_err());
+    }
+
+    #[test]
+    fn resolve_names_name_and_interval() {
+        let t = Target {
+            invert: false,
+            ranges: vec![
+                Range::Name("b".to_string()),
+                Range::NameInterval("a".to_string(), "c".to_string()),
+            ],
+        };
+        let headers = record_row(&["a", "b", "c"]);
+        let got = t.resolve_names(Some(&headers)).unwrap();
+        assert_eq!(
+            Target {
+                invert: false,
+                ranges: vec![Range::Single(1), Range::Interval(0, 2)],
+            },
+            got
+        );
+    }
+
+    #[test]
+    fn resolve_names_regex() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Regex("^addr_".to_string())],
+        };
+        let headers = record_row(&["id", "addr_a", "name", "addr_b"]);
+        let got = t.resolve_names(Some(&headers)).unwrap();
+        assert_eq!(
+            Target {
+                invert: false,
+                ranges: vec![Range::Single(1), Range::Single(3)],
+            },
+            got
+        );
+    }
+
+    #[test]
+    fn resolve_names_regex_requires_headers() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Regex("^addr_".to_string())],
+        };
+        assert!(t.resolve_names(None).is_err());
+    }
+
+    #[test]
+    fn resolve_names_invalid_regex() {
+        let t = Target {
+            invert: false,
+            ranges: vec![Range::Regex("(".to_string())],
+        };
+        let headers = record_row(&["a", "b"]);
+        assert!(t.resolve_names(Some(&headers)).is_err());
+    }
 }